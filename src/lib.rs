@@ -41,24 +41,185 @@ impl Hash {
     }
 }
 
+///! Generic Bloom filter implementation
+///! Unlike `Set`, which only accepts `u8` elements and three fixed hash
+///! functions, `BloomFilter<T>` accepts any `T: Hash` and an arbitrary
+///! number of hash functions chosen at construction time.
+///! With `new`, each hash function is a closure `Fn(&T) -> usize`, supplied
+///! by the caller, that maps an element to a bit index; the caller is
+///! responsible for keeping the returned index within the bounds of the bit
+///! array. With `with_double_hashing`, the caller supplies only `k` and the
+///! indices are derived internally.
+///!
+///! The bit array is packed into `u64` words instead of one `Vec<u8>` slot
+///! per bit, so the filter uses roughly `size / 8` bytes instead of `size`
+///! bytes. Bit `h` lives in word `h >> 6` at position `h & 63`.
+
+///! A single hash function mapping an element to a bit index
+pub type HashFn<T> = Box<dyn Fn(&T) -> usize>;
+
+///! Where a `BloomFilter<T>` gets its per-element bit indices from.
+///! `Hashers` is the original design: an explicit closure per hash
+///! function. `DoubleHashing` derives an arbitrary number of indices from
+///! just two base hashes, computed once per element, following the
+///! Kirsch-Mitzenmacher scheme.
+enum IndexSource<T: std::hash::Hash> {
+    Hashers(Vec<HashFn<T>>),
+    DoubleHashing { k: usize },
+}
+
+impl<T: std::hash::Hash> IndexSource<T> {
+    fn k(&self) -> usize {
+        match self {
+            IndexSource::Hashers(hashers) => hashers.len(),
+            IndexSource::DoubleHashing { k } => *k,
+        }
+    }
+
+    ///! Compute all `k` bit indices for `element` against a bit array of
+    ///! size `size`. For `DoubleHashing`, the two base hashes `h1` and `h2`
+    ///! are each computed exactly once, and the i-th index is derived as
+    ///! `g_i(x) = (h1 + i * h2) mod m`.
+    fn indices(&self, element: &T, size: usize) -> Vec<usize> {
+        match self {
+            IndexSource::Hashers(hashers) => hashers.iter().map(|hasher| hasher(element)).collect(),
+            IndexSource::DoubleHashing { k } => {
+                let h1 = seeded_hash(element, 0) as usize;
+                let h2 = seeded_hash(element, 1) as usize;
+                (0..*k)
+                    .map(|i| h1.wrapping_add(i.wrapping_mul(h2)) % size)
+                    .collect()
+            }
+        }
+    }
+}
+
+pub struct BloomFilter<T: std::hash::Hash> {
+    words: Vec<u64>,
+    size: usize,
+    hashers: IndexSource<T>,
+}
+
+impl<T: std::hash::Hash> BloomFilter<T> {
+    ///! Create a new generic Bloom filter with a bit array of `size` bits
+    ///! and the given hash functions. The number of hash functions (`k`)
+    ///! is simply the number of closures supplied. The backing storage is
+    ///! rounded up to a whole number of 64-bit words.
+    pub fn new(size: usize, hashers: Vec<HashFn<T>>) -> Self {
+        let word_count = size.div_ceil(64);
+        Self {
+            words: vec![0u64; word_count],
+            size,
+            hashers: IndexSource::Hashers(hashers),
+        }
+    }
+
+    ///! The number of hash functions (`k`) configured for this filter
+    pub fn k(&self) -> usize {
+        self.hashers.k()
+    }
+
+    ///! Add an element to the filter
+    ///! The bit at the index returned by each hash function is set to 1
+    pub fn add(&mut self, element: &T) {
+        for index in self.hashers.indices(element, self.size) {
+            self.words[index >> 6] |= 1 << (index & 63);
+        }
+    }
+
+    ///! Query an element in the filter
+    ///! If the bit at the index returned by every hash function is set to
+    ///! 1, the element is probably in the set
+    pub fn query(&self, element: &T) -> bool {
+        self.hashers
+            .indices(element, self.size)
+            .into_iter()
+            .all(|index| self.words[index >> 6] & (1 << (index & 63)) != 0)
+    }
+}
+
+impl<T: std::hash::Hash> BloomFilter<T> {
+    ///! Create a new Bloom filter with `k` hash functions derived from the
+    ///! Kirsch-Mitzenmacher double-hashing scheme instead of `k` independent
+    ///! hand-written hash functions.
+    ///!
+    ///! Two base hashes `h1` and `h2` are computed once per element (via
+    ///! seeded `DefaultHasher`s), and the i-th index is derived as
+    ///! `g_i(x) = (h1 + i * h2) mod m`. This gives `k` well-distributed
+    ///! indices from only two real hash computations, with false-positive
+    ///! behavior statistically equivalent to `k` independent hashes, and
+    ///! makes `k` a free runtime parameter instead of a fixed, hand-rolled
+    ///! set of formulas.
+    pub fn with_double_hashing(size: usize, k: usize) -> Self {
+        let word_count = size.div_ceil(64);
+        Self {
+            words: vec![0u64; word_count],
+            size,
+            hashers: IndexSource::DoubleHashing { k },
+        }
+    }
+}
+
+///! Hash `value` with a `DefaultHasher` seeded with `seed`, used to derive
+///! the two base hashes for double hashing.
+fn seeded_hash<T: std::hash::Hash>(value: &T, seed: u64) -> u64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::Hasher;
+
+    let mut hasher = DefaultHasher::new();
+    std::hash::Hash::hash(&seed, &mut hasher);
+    std::hash::Hash::hash(value, &mut hasher);
+    hasher.finish()
+}
+
+impl<T: std::hash::Hash> std::fmt::Display for BloomFilter<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sba = (0..self.size)
+            .map(|i| {
+                if self.words[i >> 6] & (1 << (i & 63)) != 0 {
+                    "1"
+                } else {
+                    "0"
+                }
+            })
+            .collect::<Vec<&str>>()
+            .join(" ");
+        write!(f, "{}", sba)
+    }
+}
+
 ///! Set implementation
 ///! The set is implemented as a bit array of size M
 ///! The set is initialized with 0s
 ///! When an element is added to the set, the bits at the indexes
 ///! H1(x), H2(x) and H3(x) are set to 1
+///!
+///! `Set` is a thin wrapper around `BloomFilter<u8>`, kept around for
+///! backward compatibility with code written before the generic
+///! `BloomFilter` existed. Sets made with `new`/`with_size` use the three
+///! hash functions above; sets made with `with_fp_rate` use `k` indexes
+///! derived by double hashing instead.
 pub struct Set {
-    bits: Vec<u8>,
+    inner: BloomFilter<u8>,
+    m: usize,
+    k: usize,
+    n: usize,
+    scheme: HashScheme,
+}
+
+///! Which hash scheme a `Set`'s bit indexes were computed with. Two sets
+///! must share this (as well as `m` and `k`) before their bit arrays can be
+///! combined with `union`/`intersection` — otherwise the same bit index
+///! means something different in each filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashScheme {
+    Legacy,
+    DoubleHashing,
 }
 
 impl std::fmt::Display for Set {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let sba = self
-            .bits
-            .iter()
-            .map(|&x| x.to_string())
-            .collect::<Vec<String>>()
-            .join(" ");
-        write!(f, "{}", sba)
+        write!(f, "{}", self.inner)
     }
 }
 
@@ -68,6 +229,14 @@ impl Default for Set {
     }
 }
 
+fn default_hashers(m: usize) -> Vec<HashFn<u8>> {
+    vec![
+        Box::new(move |x: &u8| Hash::hash(Hash::H1, *x, m)),
+        Box::new(move |x: &u8| Hash::hash(Hash::H2, *x, m)),
+        Box::new(move |x: &u8| Hash::hash(Hash::H3, *x, m)),
+    ]
+}
+
 impl Set {
     ///! Create a new set with a bit array of size 256
     pub fn new() -> Self {
@@ -77,27 +246,200 @@ impl Set {
     ///! Create a new set with a bit array of size M
     pub fn with_size(size: usize) -> Self {
         Self {
-            bits: vec![0u8; size],
+            inner: BloomFilter::new(size, default_hashers(size)),
+            m: size,
+            k: 3,
+            n: 0,
+            scheme: HashScheme::Legacy,
+        }
+    }
+
+    ///! Create a new set sized so that inserting `expected_items` elements
+    ///! keeps the false-positive probability around `fp_rate`.
+    ///! The bit count is `m = ceil(-n * ln(p) / (ln 2)^2)` and the number
+    ///! of hash functions is `k = ceil((m / n) * ln 2)`, following the
+    ///! standard Bloom filter sizing formulas.
+    pub fn with_fp_rate(expected_items: usize, fp_rate: f64) -> Self {
+        let n = expected_items.max(1) as f64;
+        let m = (-n * fp_rate.ln() / (std::f64::consts::LN_2.powi(2))).ceil() as usize;
+        let m = m.max(1);
+        let k = ((m as f64 / n) * std::f64::consts::LN_2).ceil() as usize;
+        let k = k.max(1);
+        Self {
+            inner: BloomFilter::with_double_hashing(m, k),
+            m,
+            k,
+            n: 0,
+            scheme: HashScheme::DoubleHashing,
         }
     }
 
     ///! Add an element to the set
     ///! The bits at the indexes H1(x), H2(x) and H3(x) are set to 1
     pub fn add(&mut self, element: u8) {
-        let m = self.bits.len();
-        self.bits[Hash::hash(Hash::H1, element, m)] = 1;
-        self.bits[Hash::hash(Hash::H2, element, m)] = 1;
-        self.bits[Hash::hash(Hash::H3, element, m)] = 1;
+        self.inner.add(&element);
+        self.n += 1;
     }
 
     ///! Query an element in the set
     ///! The bits at the indexes H1(x), H2(x) and H3(x) are checked
     ///! If all the bits are set to 1, the element is in probably in the set
     pub fn query(&mut self, element: u8) -> bool {
-        let m = self.bits.len();
-        self.bits[Hash::hash(Hash::H1, element, m)] == 1
-            && self.bits[Hash::hash(Hash::H2, element, m)] == 1
-            && self.bits[Hash::hash(Hash::H3, element, m)] == 1
+        self.inner.query(&element)
+    }
+
+    ///! Estimate the current false-positive probability of the set, based
+    ///! on the number of elements inserted so far (`n`), the bit count
+    ///! (`m`) and the number of hash functions (`k`):
+    ///! `(1 - e^{-k*n/m})^k`
+    pub fn estimated_fp_rate(&self) -> f64 {
+        let k = self.k as f64;
+        let n = self.n as f64;
+        let m = self.m as f64;
+        (1.0 - (-k * n / m).exp()).powf(k)
+    }
+
+    ///! Combine this set with `other`, bit by bit, producing a filter that
+    ///! reports an element as a member if it was inserted into either
+    ///! input set. This is exact: union introduces no new false negatives
+    ///! beyond what each input already had.
+    ///!
+    ///! Panics if the two sets don't share the same size and hash
+    ///! configuration, since their bit indexes wouldn't be comparable.
+    pub fn union(mut self, other: Self) -> Self {
+        assert_eq!(self.m, other.m, "cannot union sets of different sizes");
+        assert_eq!(
+            (self.k, self.scheme),
+            (other.k, other.scheme),
+            "cannot union sets with different hash configurations"
+        );
+        for (a, b) in self.inner.words.iter_mut().zip(other.inner.words.iter()) {
+            *a |= b;
+        }
+        self.n = self.n.max(other.n);
+        self
+    }
+
+    ///! Combine this set with `other`, bit by bit, producing a filter that
+    ///! approximates membership in both input sets. Unlike `union`, this is
+    ///! only an approximation: it can report an element as a member even if
+    ///! it was only ever inserted into one of the two inputs.
+    ///!
+    ///! Panics if the two sets don't share the same size and hash
+    ///! configuration, since their bit indexes wouldn't be comparable.
+    pub fn intersection(mut self, other: Self) -> Self {
+        assert_eq!(self.m, other.m, "cannot intersect sets of different sizes");
+        assert_eq!(
+            (self.k, self.scheme),
+            (other.k, other.scheme),
+            "cannot intersect sets with different hash configurations"
+        );
+        for (a, b) in self.inner.words.iter_mut().zip(other.inner.words.iter()) {
+            *a &= b;
+        }
+        self.n = self.n.min(other.n);
+        self
+    }
+}
+
+impl std::ops::BitOr for Set {
+    type Output = Set;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        self.union(rhs)
+    }
+}
+
+impl std::ops::BitAnd for Set {
+    type Output = Set;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        self.intersection(rhs)
+    }
+}
+
+///! Counting Bloom filter implementation
+///! Unlike `Set`, where each slot is a single bit, `CountingSet` keeps an
+///! 8-bit counter per slot. Adding an element increments the counters at
+///! H1(x), H2(x) and H3(x); removing an element decrements them. A slot is
+///! considered "set" as long as its counter is greater than zero, which is
+///! what makes removal possible.
+///! Counters saturate at `u8::MAX` instead of overflowing.
+///! Removing an element that was never added can decrement a counter that
+///! is shared with another element, which can make `query` return false
+///! for that other element afterwards. Only remove elements you are sure
+///! were actually added.
+pub struct CountingSet {
+    counters: Vec<u8>,
+}
+
+impl std::fmt::Display for CountingSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sba = self
+            .counters
+            .iter()
+            .map(|&x| x.to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        write!(f, "{}", sba)
+    }
+}
+
+impl Default for CountingSet {
+    fn default() -> Self {
+        Self::with_size(256)
+    }
+}
+
+impl CountingSet {
+    ///! Create a new counting set with a counter array of size 256
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///! Create a new counting set with a counter array of size M
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            counters: vec![0u8; size],
+        }
+    }
+
+    ///! Add an element to the set
+    ///! The counters at the indexes H1(x), H2(x) and H3(x) are incremented,
+    ///! saturating at u8::MAX
+    pub fn add(&mut self, element: u8) {
+        let m = self.counters.len();
+        self.counters[Hash::hash(Hash::H1, element, m)] =
+            self.counters[Hash::hash(Hash::H1, element, m)].saturating_add(1);
+        self.counters[Hash::hash(Hash::H2, element, m)] =
+            self.counters[Hash::hash(Hash::H2, element, m)].saturating_add(1);
+        self.counters[Hash::hash(Hash::H3, element, m)] =
+            self.counters[Hash::hash(Hash::H3, element, m)].saturating_add(1);
+    }
+
+    ///! Remove an element from the set
+    ///! The counters at the indexes H1(x), H2(x) and H3(x) are decremented,
+    ///! saturating at 0
+    ///! Removing an element that was never added can corrupt the filter,
+    ///! since its counters may be shared with other elements
+    pub fn remove(&mut self, element: u8) {
+        let m = self.counters.len();
+        self.counters[Hash::hash(Hash::H1, element, m)] =
+            self.counters[Hash::hash(Hash::H1, element, m)].saturating_sub(1);
+        self.counters[Hash::hash(Hash::H2, element, m)] =
+            self.counters[Hash::hash(Hash::H2, element, m)].saturating_sub(1);
+        self.counters[Hash::hash(Hash::H3, element, m)] =
+            self.counters[Hash::hash(Hash::H3, element, m)].saturating_sub(1);
+    }
+
+    ///! Query an element in the set
+    ///! The element is probably in the set if all of its counters are
+    ///! greater than zero
+    pub fn query(&mut self, element: u8) -> bool {
+        let m = self.counters.len();
+        self.counters[Hash::hash(Hash::H1, element, m)] > 0
+            && self.counters[Hash::hash(Hash::H2, element, m)] > 0
+            && self.counters[Hash::hash(Hash::H3, element, m)] > 0
     }
 }
 
@@ -161,4 +503,119 @@ mod test {
         assert_eq!(Hash::hash(Hash::H2, 2, m), 7);
         assert_eq!(Hash::hash(Hash::H3, 2, m), 16);
     }
+
+    #[test]
+    fn generic_bloom_filter_with_string_elements() {
+        let m = 64;
+        let hashers: Vec<HashFn<String>> = vec![
+            Box::new(move |s: &String| s.len() % m),
+            Box::new(move |s: &String| s.bytes().map(|b| b as usize).sum::<usize>() % m),
+        ];
+        let mut filter = BloomFilter::new(m, hashers);
+        filter.add(&"hello".to_string());
+        assert_eq!(filter.query(&"hello".to_string()), true);
+        assert_eq!(filter.query(&"goodbye".to_string()), false);
+    }
+
+    #[test]
+    fn counting_set_add_and_query() {
+        let mut filter = CountingSet::new();
+        filter.add(1);
+        filter.add(2);
+        filter.add(3);
+        assert_eq!(filter.query(1), true);
+        assert_eq!(filter.query(2), true);
+        assert_eq!(filter.query(3), true);
+        assert_eq!(filter.query(4), false);
+    }
+
+    #[test]
+    fn counting_set_remove() {
+        let mut filter = CountingSet::new();
+        filter.add(1);
+        assert_eq!(filter.query(1), true);
+        filter.remove(1);
+        assert_eq!(filter.query(1), false);
+    }
+
+    #[test]
+    fn counting_set_remove_is_saturating() {
+        let mut filter = CountingSet::with_size(5);
+        filter.remove(1);
+        filter.remove(1);
+        assert_eq!(filter.query(1), false);
+    }
+
+    #[test]
+    fn with_fp_rate_sizes_the_set_and_tracks_estimate() {
+        let mut filter = Set::with_fp_rate(100, 0.01);
+        assert_eq!(filter.estimated_fp_rate(), 0.0);
+        filter.add(1);
+        filter.add(2);
+        assert!(filter.query(1));
+        assert!(filter.query(2));
+        assert!(filter.estimated_fp_rate() > 0.0);
+        assert!(filter.estimated_fp_rate() < 0.01);
+    }
+
+    #[test]
+    fn double_hashing_finds_added_elements_and_rejects_others() {
+        let mut filter: BloomFilter<u8> = BloomFilter::with_double_hashing(256, 10);
+        filter.add(&1);
+        filter.add(&2);
+        assert!(filter.query(&1));
+        assert!(filter.query(&2));
+        assert!(!filter.query(&3));
+    }
+
+    #[test]
+    fn display_is_packed_bit_accurate_for_non_word_aligned_sizes() {
+        let mut filter = Set::with_size(5);
+        filter.add(9);
+        assert_eq!(format!("{}", filter).split(' ').count(), 5);
+    }
+
+    #[test]
+    fn union_reports_membership_in_either_set() {
+        let mut a = Set::with_size(256);
+        a.add(1);
+        let mut b = Set::with_size(256);
+        b.add(2);
+
+        let mut union = a | b;
+        assert_eq!(union.query(1), true);
+        assert_eq!(union.query(2), true);
+        assert_eq!(union.query(3), false);
+    }
+
+    #[test]
+    fn intersection_reports_membership_in_both_sets() {
+        let mut a = Set::with_size(256);
+        a.add(1);
+        a.add(2);
+        let mut b = Set::with_size(256);
+        b.add(2);
+        b.add(3);
+
+        let mut intersection = a & b;
+        assert_eq!(intersection.query(1), false);
+        assert_eq!(intersection.query(2), true);
+        assert_eq!(intersection.query(3), false);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot union sets of different sizes")]
+    fn union_panics_on_mismatched_sizes() {
+        let a = Set::with_size(256);
+        let b = Set::with_size(128);
+        let _ = a | b;
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot union sets with different hash configurations")]
+    fn union_panics_on_matching_m_and_k_but_different_hash_scheme() {
+        let a = Set::with_size(4);
+        let b = Set::with_fp_rate(1, 0.15);
+        let _ = a | b;
+    }
 }